@@ -8,9 +8,13 @@ import { WebRTCManager, Logger } from './webrtcService';
 
 export default function WebRTCTestPage() {
     const [roomId, setRoomId] = useState('test-room');
-    const [isCameraOn, setIsCameraOn] = useState(true);
-    const [isMicrophoneOn, setIsMicrophoneOn] = useState(true);
     const [webrtcManager, setWebrtcManager] = useState<WebRTCManager | null>(null);
+    // Bumped whenever the manager's media state changes; the buttons read it from the manager
+    const [, setRevision] = useState(0);
+
+    const isCameraOn = webrtcManager?.isCameraOn() ?? false;
+    const isMicrophoneOn = webrtcManager?.isMicrophoneOn() ?? false;
+    const isSharingScreen = webrtcManager?.isSharingScreen() ?? false;
 
     const localVideoRef = useRef<HTMLVideoElement>(null);
     const remoteVideoRef = useRef<HTMLVideoElement>(null);
@@ -36,10 +40,9 @@ export default function WebRTCTestPage() {
                 logger,
             );
 
+            manager.onStateChange = () => setRevision((revision) => revision + 1);
             setWebrtcManager(manager);
             await manager.joinRoom();
-            setIsCameraOn(true);
-            setIsMicrophoneOn(true);
         }
     }, [roomId, logger]);
 
@@ -47,22 +50,28 @@ export default function WebRTCTestPage() {
         if (webrtcManager) {
             webrtcManager.leaveRoom();
             setWebrtcManager(null);
-            setIsCameraOn(false);
-            setIsMicrophoneOn(false);
         }
     }, [webrtcManager]);
 
     const toggleCamera = useCallback(() => {
         if (webrtcManager) {
             webrtcManager.toggleCamera();
-            setIsCameraOn((prev) => !prev);
         }
     }, [webrtcManager]);
 
     const toggleMicrophone = useCallback(() => {
         if (webrtcManager) {
             webrtcManager.toggleMicrophone();
-            setIsMicrophoneOn((prev) => !prev);
+        }
+    }, [webrtcManager]);
+
+    const toggleScreenShare = useCallback(async () => {
+        if (webrtcManager) {
+            if (webrtcManager.isSharingScreen()) {
+                await webrtcManager.stopScreenShare();
+            } else {
+                await webrtcManager.startScreenShare();
+            }
         }
     }, [webrtcManager]);
 
@@ -118,6 +127,12 @@ export default function WebRTCTestPage() {
                             >
                                 {isMicrophoneOn ? 'Turn Mic Off' : 'Turn Mic On'}
                             </Button>
+                            <Button
+                                color={isSharingScreen ? 'primary' : 'default'}
+                                onPress={toggleScreenShare}
+                            >
+                                {isSharingScreen ? 'Stop Sharing' : 'Share Screen'}
+                            </Button>
                         </div>
                     </CardBody>
                 </Card>
@@ -1,5 +1,16 @@
 export type LogType = 'local' | 'remote';
 export type Logger = (type: LogType, message: string) => void;
+export type TrackKind = 'camera' | 'mic' | 'screen';
+export type Role = 'moderator' | 'presenter' | 'viewer';
+
+// Close codes sent by the signaling server, see backend/src/close_codes.rs.
+const CLOSE_REASONS: Record<number, string> = {
+    4001: 'authentication failed',
+    4002: 'too many invalid or rate-limited messages',
+    4003: 'server is shutting down',
+    4004: 'not found',
+    4005: 'removed by a moderator',
+};
 
 export class WebRTCManager {
     roomId: string;
@@ -7,11 +18,24 @@ export class WebRTCManager {
     localVideo: HTMLVideoElement;
     remoteVideo: HTMLVideoElement;
     logger: Logger;
+    // Called whenever camera, microphone or screen sharing state changes
+    onStateChange: (() => void) | null = null;
 
     peerConnection: RTCPeerConnection | null = null;
     socket: WebSocket | null = null;
     localStream: MediaStream | null = null;
     otherUserId: string | null = null;
+    userId: string | null = null;
+    role: Role | null = null;
+    localSenders: RTCRtpSender[] = [];
+    reconnectTimer: ReturnType<typeof setTimeout> | null = null;
+
+    screenStream: MediaStream | null = null;
+    screenSender: RTCRtpSender | null = null;
+    remoteCameraStream: MediaStream | null = null;
+    remoteScreenStream: MediaStream | null = null;
+    remoteScreenStreamId: string | null = null;
+    remoteScreenTrackId: string | null = null;
 
     constructor(
         localVideo: HTMLVideoElement,
@@ -50,19 +74,15 @@ export class WebRTCManager {
             this.localVideo.srcObject = stream;
             this.localStream = stream;
             this.logger('local', 'Local stream acquired.');
+            this.onStateChange?.();
 
-            const config = {
+            // Replaced by the server's ice_config (with TURN) before anything is negotiated
+            const pc = new RTCPeerConnection({
                 iceServers: [{ urls: 'stun:stun.l.google.com:19302' }],
-            };
-            const pc = new RTCPeerConnection(config);
+            });
 
             this.peerConnection = pc;
 
-            stream.getTracks().forEach((track) => {
-                pc.addTrack(track, stream);
-            });
-            this.logger('local', 'Tracks added to PeerConnection.');
-
             pc.onicecandidate = (event) => {
                 if (event.candidate && this.socket && this.socket.readyState === WebSocket.OPEN) {
                     this.logger('local', 'Sending ICE candidate');
@@ -77,8 +97,16 @@ export class WebRTCManager {
             };
 
             pc.ontrack = (event) => {
-                this.logger('remote', 'Received remote track');
-                this.remoteVideo.srcObject = event.streams[0];
+                const remoteStream = event.streams[0];
+
+                if (remoteStream.id === this.remoteScreenStreamId) {
+                    this.logger('remote', 'Received remote screen share');
+                    this.remoteScreenStream = remoteStream;
+                } else {
+                    this.logger('remote', 'Received remote track');
+                    this.remoteCameraStream = remoteStream;
+                }
+                this.showRemoteStream();
             };
 
             this.logger('local', 'WebRTC setup complete.');
@@ -106,27 +134,6 @@ export class WebRTCManager {
                 }),
             );
             this.logger('local', `Sent join request for room: ${this.roomId}`);
-
-            if (this.peerConnection) {
-                this.logger('local', 'Creating offer...');
-                this.peerConnection
-                    .createOffer()
-                    .then((offer) => {
-                        return this.peerConnection!.setLocalDescription(offer).then(() => {
-                            ws.send(
-                                JSON.stringify({
-                                    type: 'offer',
-                                    offer: offer,
-                                    room: this.roomId,
-                                }),
-                            );
-                            this.logger('local', 'Offer sent.');
-                        });
-                    })
-                    .catch((error: any) => {
-                        this.logger('local', 'Error creating or sending offer: ' + error.message);
-                    });
-            }
         };
 
         ws.onmessage = async (event) => {
@@ -143,6 +150,120 @@ export class WebRTCManager {
                 const pc = this.peerConnection;
 
                 switch (message.type) {
+                    case 'ice_config':
+                        pc.setConfiguration({
+                            ...pc.getConfiguration(),
+                            iceServers: message.iceServers,
+                        });
+                        this.logger(
+                            'local',
+                            `ICE config received (${message.iceServers.length} servers).`,
+                        );
+                        break;
+
+                    case 'draining': {
+                        // Reconnect while this instance still serves us; the jitter keeps
+                        // all clients from hitting the next instance at once
+                        const delay = Math.random() * Math.min(message.grace_secs, 10) * 1000;
+
+                        this.logger('local', `Server is draining, reconnecting in ${delay | 0} ms`);
+                        this.reconnectTimer = setTimeout(() => this.joinRoom(), delay);
+                        break;
+                    }
+
+                    case 'room_state':
+                        this.userId = message.user_id;
+                        this.role = message.role;
+                        this.logger('local', `Joined as ${message.role}`);
+                        this.logger(
+                            'local',
+                            `Room has ${message.participants.length} other participant(s)`,
+                        );
+                        message.participants.forEach((participant: any) => {
+                            participant.tracks.forEach((track: any) =>
+                                this.handleRemoteTrackAdded(track),
+                            );
+                        });
+                        // Offer only after the role is known, viewers negotiate receive-only
+                        if (this.canPublish()) {
+                            await this.startPublishing();
+                        } else {
+                            pc.addTransceiver('video', { direction: 'recvonly' });
+                            pc.addTransceiver('audio', { direction: 'recvonly' });
+                            await this.renegotiate();
+                        }
+                        break;
+
+                    case 'track_added':
+                        this.logger('remote', `${message.from} added ${message.track.kind} track`);
+                        this.handleRemoteTrackAdded(message.track);
+                        break;
+
+                    case 'track_removed':
+                        this.logger('remote', `${message.from} removed track ${message.track_id}`);
+                        if (message.track_id === this.remoteScreenTrackId) {
+                            this.remoteScreenStream = null;
+                            this.remoteScreenStreamId = null;
+                            this.remoteScreenTrackId = null;
+                            this.showRemoteStream();
+                        }
+                        break;
+
+                    case 'track_muted':
+                        this.logger(
+                            'remote',
+                            `${message.from} ${message.muted ? 'muted' : 'unmuted'} ` +
+                                `track ${message.track_id}`,
+                        );
+                        break;
+
+                    case 'role_changed':
+                        this.logger('remote', `${message.user_id} is now ${message.role}`);
+                        if (message.user_id === this.userId) {
+                            const couldPublish = this.canPublish();
+
+                            this.role = message.role;
+                            if (couldPublish && !this.canPublish()) {
+                                await this.stopPublishing();
+                            } else if (!couldPublish && this.canPublish()) {
+                                await this.startPublishing();
+                            }
+                        }
+                        break;
+
+                    case 'mute_user':
+                        this.logger('remote', `Muted by moderator ${message.from}`);
+                        this.localStream?.getTracks().forEach((track) => {
+                            const kind = track.kind === 'video' ? 'camera' : 'mic';
+
+                            if (track.enabled && (!message.kind || message.kind === kind)) {
+                                track.enabled = false;
+                                this.sendSignal({
+                                    type: 'track_muted',
+                                    track_id: track.id,
+                                    muted: true,
+                                });
+                            }
+                        });
+                        this.onStateChange?.();
+                        break;
+
+                    case 'removed':
+                        this.logger('remote', `Removed from room by moderator ${message.from}`);
+                        this.cleanupConnections();
+                        break;
+
+                    case 'error':
+                        this.logger('local', `Signaling error: ${message.reason}`);
+                        break;
+
+                    case 'peer_left':
+                        this.logger('remote', `${message.from} left the room`);
+                        this.remoteCameraStream = null;
+                        this.remoteScreenStream = null;
+                        this.showRemoteStream();
+                        break;
+
                     case 'offer':
                         if (message.from && message.from === (ws as any).userId) {
                             this.logger('local', 'Ignoring own offer.');
@@ -195,8 +316,12 @@ export class WebRTCManager {
             }
         };
 
-        ws.onclose = () => {
-            this.logger('local', 'Disconnected from signaling server');
+        ws.onclose = (event) => {
+            const reason = CLOSE_REASONS[event.code] ?? event.reason;
+            this.logger(
+                'local',
+                `Disconnected from signaling server (${event.code}${reason ? ': ' + reason : ''})`,
+            );
         };
 
         ws.onerror = (error) => {
@@ -204,6 +329,150 @@ export class WebRTCManager {
         };
     }
 
+    sendSignal(message: object) {
+        if (this.socket && this.socket.readyState === WebSocket.OPEN) {
+            this.socket.send(JSON.stringify({ ...message, room: this.roomId }));
+        }
+    }
+
+    muteUser(target: string, kind?: TrackKind) {
+        this.sendSignal({ type: 'mute_user', target, kind });
+    }
+
+    removeUser(target: string) {
+        this.sendSignal({ type: 'remove_user', target });
+    }
+
+    setRole(target: string, role: Role) {
+        this.sendSignal({ type: 'set_role', target, role });
+    }
+
+    isCameraOn(): boolean {
+        return this.localStream?.getVideoTracks()[0]?.enabled ?? false;
+    }
+
+    isMicrophoneOn(): boolean {
+        return this.localStream?.getAudioTracks()[0]?.enabled ?? false;
+    }
+
+    isSharingScreen(): boolean {
+        return this.screenStream !== null;
+    }
+
+    canPublish(): boolean {
+        return this.role !== null && this.role !== 'viewer';
+    }
+
+    async startPublishing() {
+        if (!this.peerConnection || !this.localStream || this.localSenders.length > 0) {
+            return;
+        }
+        const pc = this.peerConnection;
+        const stream = this.localStream;
+
+        stream.getTracks().forEach((track) => {
+            const kind = track.kind === 'video' ? 'camera' : 'mic';
+
+            this.localSenders.push(pc.addTrack(track, stream));
+            this.sendTrackAdded(track, kind, stream);
+        });
+        this.logger('local', 'Tracks added to PeerConnection.');
+        await this.renegotiate();
+    }
+
+    // The server has already dropped our tracks, so no track_removed is sent here
+    async stopPublishing() {
+        if (!this.peerConnection) {
+            return;
+        }
+        const pc = this.peerConnection;
+
+        this.localSenders.forEach((sender) => pc.removeTrack(sender));
+        this.localSenders = [];
+        if (this.screenStream) {
+            if (this.screenSender) {
+                pc.removeTrack(this.screenSender);
+            }
+            this.screenStream.getTracks().forEach((t) => t.stop());
+            this.screenStream = null;
+            this.screenSender = null;
+        }
+        await this.renegotiate();
+        this.logger('local', 'Publishing stopped, now a viewer.');
+        this.onStateChange?.();
+    }
+
+    sendTrackAdded(track: MediaStreamTrack, kind: TrackKind, stream: MediaStream) {
+        this.sendSignal({
+            type: 'track_added',
+            track: { id: track.id, kind, stream_id: stream.id, muted: !track.enabled },
+        });
+    }
+
+    handleRemoteTrackAdded(track: any) {
+        if (track.kind === 'screen') {
+            this.remoteScreenStreamId = track.stream_id ?? null;
+            this.remoteScreenTrackId = track.id;
+        }
+    }
+
+    showRemoteStream() {
+        this.remoteVideo.srcObject = this.remoteScreenStream ?? this.remoteCameraStream;
+    }
+
+    async renegotiate() {
+        if (!this.peerConnection) {
+            return;
+        }
+        const offer = await this.peerConnection.createOffer();
+
+        await this.peerConnection.setLocalDescription(offer);
+        this.sendSignal({ type: 'offer', offer: offer });
+        this.logger('local', 'Renegotiation offer sent.');
+    }
+
+    async startScreenShare(): Promise<boolean> {
+        if (!this.peerConnection || this.screenStream || !this.canPublish()) {
+            return false;
+        }
+        try {
+            const stream = await navigator.mediaDevices.getDisplayMedia({ video: true });
+            const track = stream.getVideoTracks()[0];
+
+            this.screenStream = stream;
+            this.screenSender = this.peerConnection.addTrack(track, stream);
+            track.onended = () => this.stopScreenShare();
+            this.sendTrackAdded(track, 'screen', stream);
+            await this.renegotiate();
+            this.logger('local', 'Screen sharing started.');
+            this.onStateChange?.();
+
+            return true;
+        } catch (error: any) {
+            this.logger('local', 'Error starting screen share: ' + error.message);
+
+            return false;
+        }
+    }
+
+    async stopScreenShare() {
+        if (!this.screenStream) {
+            return;
+        }
+        const track = this.screenStream.getVideoTracks()[0];
+
+        if (this.peerConnection && this.screenSender) {
+            this.peerConnection.removeTrack(this.screenSender);
+        }
+        this.screenStream.getTracks().forEach((t) => t.stop());
+        this.screenStream = null;
+        this.screenSender = null;
+        this.sendSignal({ type: 'track_removed', track_id: track.id });
+        await this.renegotiate();
+        this.logger('local', 'Screen sharing stopped.');
+        this.onStateChange?.();
+    }
+
     async joinRoom(): Promise<void> {
         this.cleanupConnections();
         const rtcReady = await this.setupWebRTC();
@@ -224,7 +493,13 @@ export class WebRTCManager {
 
             if (videoTrack) {
                 videoTrack.enabled = !videoTrack.enabled;
+                this.sendSignal({
+                    type: 'track_muted',
+                    track_id: videoTrack.id,
+                    muted: !videoTrack.enabled,
+                });
                 this.logger('local', `Camera ${videoTrack.enabled ? 'turned ON' : 'turned OFF'}`);
+                this.onStateChange?.();
             }
         }
     }
@@ -235,15 +510,25 @@ export class WebRTCManager {
 
             if (audioTrack) {
                 audioTrack.enabled = !audioTrack.enabled;
+                this.sendSignal({
+                    type: 'track_muted',
+                    track_id: audioTrack.id,
+                    muted: !audioTrack.enabled,
+                });
                 this.logger(
                     'local',
                     `Microphone ${audioTrack.enabled ? 'turned ON' : 'turned OFF'}`,
                 );
+                this.onStateChange?.();
             }
         }
     }
 
     cleanupConnections() {
+        if (this.reconnectTimer) {
+            clearTimeout(this.reconnectTimer);
+            this.reconnectTimer = null;
+        }
         if (this.peerConnection) {
             this.peerConnection.close();
             this.peerConnection = null;
@@ -259,7 +544,20 @@ export class WebRTCManager {
             this.localStream = null;
             this.logger('local', 'Previous local stream stopped.');
         }
+        if (this.screenStream) {
+            this.screenStream.getTracks().forEach((track) => track.stop());
+            this.screenStream = null;
+            this.screenSender = null;
+        }
+        this.localSenders = [];
+        this.userId = null;
+        this.role = null;
+        this.remoteCameraStream = null;
+        this.remoteScreenStream = null;
+        this.remoteScreenStreamId = null;
+        this.remoteScreenTrackId = null;
         this.localVideo.srcObject = null;
         this.remoteVideo.srcObject = null;
+        this.onStateChange?.();
     }
 }
@@ -0,0 +1,17 @@
+//! Close codes the server uses when it terminates a WebSocket, so clients can tell
+//! why they were disconnected. 4000-4999 is the application range of RFC 6455.
+
+/// Authentication failed. Reserved: the signaling server does not authenticate yet.
+pub const AUTH_FAILED: u16 = 4001;
+
+/// Too many rate-limited or malformed messages.
+pub const RATE_LIMITED: u16 = 4002;
+
+/// The server is shutting down; reconnect, possibly to another instance.
+pub const DRAINED: u16 = 4003;
+
+/// The requested room or resource does not exist. Reserved.
+pub const NOT_FOUND: u16 = 4004;
+
+/// A moderator removed the client from its only room.
+pub const REMOVED: u16 = 4005;
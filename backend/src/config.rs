@@ -0,0 +1,393 @@
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use log::{info, warn};
+
+pub struct Config {
+    /// Port the HTTP/WebSocket server listens on.
+    pub port: u16,
+    /// STUN servers handed to every client.
+    pub stun_urls: Vec<String>,
+    /// TURN servers; only advertised when `turn_secret` is set.
+    pub turn_urls: Vec<String>,
+    /// Shared secret with the TURN server (coturn `static-auth-secret`). Credentials minted
+    /// with it go out only over `/signaling`, so they are as easy to get as a signaling
+    /// connection: anyone who can open one can relay through the TURN server until the
+    /// credentials expire.
+    pub turn_secret: Option<String>,
+    /// Lifetime of minted TURN credentials, in seconds.
+    pub turn_credential_ttl: u64,
+    /// Concurrent WebSocket connections allowed from a single IP.
+    pub max_connections_per_ip: usize,
+    /// Take the client IP from the last `X-Forwarded-For` entry; only safe behind a reverse proxy.
+    pub trust_forwarded_for: bool,
+    /// Largest incoming WebSocket message, in bytes.
+    pub max_message_size: usize,
+    /// Sustained incoming messages per second per connection.
+    pub message_rate_limit: u32,
+    /// Messages a connection may send in a burst, i.e. the size of the token bucket.
+    pub message_burst: u32,
+    /// Violations tolerated before a connection is closed.
+    pub max_strikes: u32,
+    /// How long clients get to leave on their own after a shutdown signal.
+    pub drain_grace_period: Duration,
+    /// Every setting as read, for the startup summary.
+    settings: Vec<Setting>,
+}
+
+/// A setting's display value (secrets already masked) and where it came from.
+struct Setting {
+    key: &'static str,
+    value: String,
+    source: Source,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Source {
+    Env,
+    Default,
+}
+
+/// An environment variable that failed to parse or validate.
+#[derive(Debug)]
+pub struct ConfigError {
+    pub key: &'static str,
+    pub value: String,
+    pub expected: &'static str,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={:?} is invalid, expected {}", self.key, self.value, self.expected)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Reads the configuration from the environment. Invalid values fall back to their
+    /// defaults with a warning, or fail with an error when `CONFIG_STRICT` is set.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::from_lookup(|key| env::var(key).ok())
+    }
+
+    /// Like `from_env`, but reads the variables through `lookup`, e.g. to embed the
+    /// server in tests without touching the process environment.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        let mut reader = EnvReader {
+            lookup,
+            strict: false,
+            settings: Vec::new(),
+        };
+        reader.strict = reader.strict_mode();
+
+        let port = reader.parsed("PORT", 3030u16, |p| *p != 0, "a port number between 1 and 65535")?;
+        let stun_urls = reader.list(
+            "STUN_URLS",
+            vec!["stun:stun.l.google.com:19302".to_string()],
+            &["stun:", "stuns:"],
+            "a comma-separated list of stun:/stuns: URLs",
+        )?;
+        let turn_urls = reader.list(
+            "TURN_URLS",
+            Vec::new(),
+            &["turn:", "turns:"],
+            "a comma-separated list of turn:/turns: URLs",
+        )?;
+        let turn_secret = reader.secret("TURN_SECRET");
+        let turn_credential_ttl = reader.parsed(
+            "TURN_CREDENTIAL_TTL",
+            3600u64,
+            |ttl| (60..=7 * 24 * 3600).contains(ttl),
+            "seconds between 60 and 604800",
+        )?;
+        let max_connections_per_ip = reader.parsed(
+            "MAX_CONNECTIONS_PER_IP",
+            20usize,
+            |n| (1..=10_000).contains(n),
+            "a number between 1 and 10000",
+        )?;
+        let trust_forwarded_for = reader.flag("TRUST_FORWARDED_FOR", false)?;
+        let max_message_size = reader.parsed(
+            "MAX_MESSAGE_SIZE",
+            64 * 1024usize,
+            |n| (1024..=16 * 1024 * 1024).contains(n),
+            "bytes between 1024 and 16777216",
+        )?;
+        let message_rate_limit = reader.parsed(
+            "MESSAGE_RATE_LIMIT",
+            20u32,
+            |n| (1..=1000).contains(n),
+            "messages per second between 1 and 1000",
+        )?;
+        let message_burst = reader.parsed(
+            "MESSAGE_BURST",
+            100u32,
+            |n| (1..=10_000).contains(n),
+            "a number between 1 and 10000",
+        )?;
+        let max_strikes = reader.parsed("MAX_STRIKES", 10u32, |n| (1..=1000).contains(n), "a number between 1 and 1000")?;
+        let drain_grace_period = reader.parsed(
+            "DRAIN_GRACE_PERIOD",
+            30u64,
+            |secs| *secs <= 3600,
+            "seconds between 0 and 3600",
+        )?;
+
+        if turn_secret.is_none() && !turn_urls.is_empty() {
+            let error = ConfigError {
+                key: "TURN_SECRET",
+                value: String::new(),
+                expected: "a secret when TURN_URLS is set",
+            };
+            if reader.strict {
+                return Err(error);
+            }
+            warn!("{}, TURN-серверы не будут выданы клиентам", error);
+        }
+
+        Ok(Self {
+            port,
+            stun_urls,
+            turn_urls,
+            turn_secret,
+            turn_credential_ttl,
+            max_connections_per_ip,
+            trust_forwarded_for,
+            max_message_size,
+            message_rate_limit,
+            message_burst,
+            max_strikes,
+            drain_grace_period: Duration::from_secs(drain_grace_period),
+            settings: reader.settings,
+        })
+    }
+
+    /// Logs every setting with its value (secrets masked) and whether it came from the environment.
+    pub fn log_summary(&self) {
+        for setting in &self.settings {
+            let source = match setting.source {
+                Source::Env => "env",
+                Source::Default => "default",
+            };
+            info!("Конфигурация: {}={} ({})", setting.key, setting.value, source);
+        }
+    }
+}
+
+struct EnvReader<F> {
+    lookup: F,
+    strict: bool,
+    settings: Vec<Setting>,
+}
+
+impl<F: Fn(&str) -> Option<String>> EnvReader<F> {
+    fn parsed<T: FromStr + fmt::Display>(
+        &mut self,
+        key: &'static str,
+        default: T,
+        valid: impl Fn(&T) -> bool,
+        expected: &'static str,
+    ) -> Result<T, ConfigError> {
+        self.read(key, default, |raw| raw.parse().ok().filter(&valid), expected)
+    }
+
+    /// Reads `CONFIG_STRICT`. A value that doesn't parse still enables strict mode,
+    /// whoever set the variable asked for it.
+    fn strict_mode(&mut self) -> bool {
+        const KEY: &str = "CONFIG_STRICT";
+        let Some(raw) = (self.lookup)(KEY) else {
+            self.record(KEY, false.to_string(), Source::Default);
+            return false;
+        };
+        let strict = parse_flag(&raw).unwrap_or_else(|| {
+            let error = ConfigError {
+                key: KEY,
+                value: raw,
+                expected: "true/false or 1/0",
+            };
+            warn!("{}, включён строгий режим", error);
+            true
+        });
+        self.record(KEY, strict.to_string(), Source::Env);
+        strict
+    }
+
+    /// A boolean setting, see `parse_flag`.
+    fn flag(&mut self, key: &'static str, default: bool) -> Result<bool, ConfigError> {
+        self.read(key, default, parse_flag, "true/false or 1/0")
+    }
+
+    fn read<T: fmt::Display>(
+        &mut self,
+        key: &'static str,
+        default: T,
+        parse: impl Fn(&str) -> Option<T>,
+        expected: &'static str,
+    ) -> Result<T, ConfigError> {
+        let raw = match (self.lookup)(key) {
+            Some(raw) => raw,
+            None => {
+                self.record(key, default.to_string(), Source::Default);
+                return Ok(default);
+            }
+        };
+        match parse(raw.trim()) {
+            Some(value) => {
+                self.record(key, value.to_string(), Source::Env);
+                Ok(value)
+            }
+            None => self.invalid(key, raw, expected, default.to_string()).map(|_| default),
+        }
+    }
+
+    fn list(
+        &mut self,
+        key: &'static str,
+        default: Vec<String>,
+        schemes: &[&str],
+        expected: &'static str,
+    ) -> Result<Vec<String>, ConfigError> {
+        let raw = match (self.lookup)(key) {
+            Some(raw) => raw,
+            None => {
+                self.record(key, default.join(","), Source::Default);
+                return Ok(default);
+            }
+        };
+        let values = split_list(&raw);
+        if values.iter().all(|v| schemes.iter().any(|scheme| v.starts_with(scheme))) {
+            self.record(key, values.join(","), Source::Env);
+            Ok(values)
+        } else {
+            self.invalid(key, raw, expected, default.join(",")).map(|_| default)
+        }
+    }
+
+    /// Like a plain string setting, but only ever recorded masked.
+    fn secret(&mut self, key: &'static str) -> Option<String> {
+        let value = (self.lookup)(key).filter(|v| !v.is_empty());
+        match value {
+            Some(_) => self.record(key, "***".to_string(), Source::Env),
+            None => self.record(key, String::new(), Source::Default),
+        }
+        value
+    }
+
+    /// In strict mode turns a bad value into an error, otherwise warns and records the default.
+    fn invalid(
+        &mut self,
+        key: &'static str,
+        value: String,
+        expected: &'static str,
+        default: String,
+    ) -> Result<(), ConfigError> {
+        let error = ConfigError { key, value, expected };
+        if self.strict {
+            return Err(error);
+        }
+        warn!("{}, используется значение по умолчанию", error);
+        self.record(key, default, Source::Default);
+        Ok(())
+    }
+
+    fn record(&mut self, key: &'static str, value: String, source: Source) {
+        self.settings.push(Setting { key, value, source });
+    }
+}
+
+/// Booleans are `true`/`false` or `1`/`0`, case-insensitive.
+fn parse_flag(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" => Some(true),
+        "0" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(vars: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        Config::from_lookup(|key| vars.iter().find(|(k, _)| *k == key).map(|(_, v)| v.to_string()))
+    }
+
+    #[test]
+    fn defaults_when_unset() {
+        let config = config(&[]).unwrap();
+        assert_eq!(config.port, 3030);
+        assert_eq!(config.stun_urls, ["stun:stun.l.google.com:19302"]);
+        assert!(config.turn_secret.is_none());
+        assert_eq!(config.max_connections_per_ip, 20);
+        assert!(!config.trust_forwarded_for);
+        assert_eq!(config.drain_grace_period, Duration::from_secs(30));
+        assert!(config.settings.iter().all(|s| s.source == Source::Default));
+    }
+
+    #[test]
+    fn invalid_value_falls_back_to_default() {
+        let config = config(&[("PORT", "80a0")]).unwrap();
+        assert_eq!(config.port, 3030);
+        let port = config.settings.iter().find(|s| s.key == "PORT").unwrap();
+        assert_eq!((port.value.as_str(), port.source), ("3030", Source::Default));
+    }
+
+    #[test]
+    fn strict_mode_rejects_invalid_value() {
+        let error = config(&[("CONFIG_STRICT", "1"), ("PORT", "80a0")]).err().unwrap();
+        assert_eq!((error.key, error.value.as_str()), ("PORT", "80a0"));
+
+        assert!(config(&[("CONFIG_STRICT", "true"), ("TURN_URLS", "turn:example.org")]).is_err());
+        assert!(config(&[("CONFIG_STRICT", "true"), ("STUN_URLS", "http://example.org")]).is_err());
+        assert!(config(&[("CONFIG_STRICT", "true"), ("PORT", "0")]).is_err());
+    }
+
+    #[test]
+    fn unparseable_strict_flag_means_strict() {
+        assert!(config(&[("CONFIG_STRICT", "yes"), ("PORT", "80a0")]).is_err());
+        let config = config(&[("CONFIG_STRICT", "yes")]).unwrap();
+        let strict = config.settings.iter().find(|s| s.key == "CONFIG_STRICT").unwrap();
+        assert_eq!(strict.value, "true");
+    }
+
+    #[test]
+    fn reads_values_from_env() {
+        let config = config(&[
+            ("PORT", " 8080 "),
+            ("STUN_URLS", "stun:a.example:3478, stuns:b.example"),
+            ("TRUST_FORWARDED_FOR", "TRUE"),
+            ("DRAIN_GRACE_PERIOD", "0"),
+        ])
+        .unwrap();
+        assert_eq!(config.port, 8080);
+        assert_eq!(config.stun_urls, ["stun:a.example:3478", "stuns:b.example"]);
+        assert!(config.trust_forwarded_for);
+        assert_eq!(config.drain_grace_period, Duration::ZERO);
+    }
+
+    #[test]
+    fn secret_is_recorded_masked() {
+        let config = config(&[("TURN_SECRET", "hunter2"), ("TURN_URLS", "turn:example.org")]).unwrap();
+        assert_eq!(config.turn_secret.as_deref(), Some("hunter2"));
+        let secret = config.settings.iter().find(|s| s.key == "TURN_SECRET").unwrap();
+        assert_eq!(secret.value, "***");
+    }
+
+    #[test]
+    fn flags() {
+        for (value, expected) in [("1", Some(true)), ("True", Some(true)), ("0", Some(false)), ("no", None)] {
+            assert_eq!(parse_flag(value), expected, "{}", value);
+        }
+    }
+}
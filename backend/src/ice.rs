@@ -0,0 +1,104 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha1::Sha1;
+use uuid::Uuid;
+use crate::config::Config;
+
+type HmacSha1 = Hmac<Sha1>;
+
+/// Mirrors the browser `RTCIceServer` dictionary.
+#[derive(Serialize)]
+pub struct IceServer {
+    pub urls: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credential: Option<String>,
+}
+
+/// Sent to each client as `ice_config` when its signaling connection opens, directly
+/// usable as `RTCConfiguration`.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IceConfig {
+    pub ice_servers: Vec<IceServer>,
+    /// Seconds until the TURN credentials expire.
+    pub ttl: u64,
+}
+
+impl IceConfig {
+    /// Builds the ICE server list, minting fresh TURN credentials if a secret is configured.
+    pub fn generate(config: &Config) -> Self {
+        let mut ice_servers = Vec::new();
+
+        if !config.stun_urls.is_empty() {
+            ice_servers.push(IceServer {
+                urls: config.stun_urls.clone(),
+                username: None,
+                credential: None,
+            });
+        }
+
+        if let Some(secret) = &config.turn_secret {
+            if !config.turn_urls.is_empty() {
+                let (username, credential) =
+                    turn_credentials(secret, config.turn_credential_ttl, &Uuid::new_v4().to_string());
+                ice_servers.push(IceServer {
+                    urls: config.turn_urls.clone(),
+                    username: Some(username),
+                    credential: Some(credential),
+                });
+            }
+        }
+
+        Self {
+            ice_servers,
+            ttl: config.turn_credential_ttl,
+        }
+    }
+}
+
+/// Time-limited credentials in the TURN REST API format understood by coturn:
+/// username is `<expiry unix time>:<user>`, password is base64(HMAC-SHA1(secret, username)).
+fn turn_credentials(secret: &str, ttl: u64, user: &str) -> (String, String) {
+    let expires_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+        + ttl;
+    let username = format!("{}:{}", expires_at, user);
+    let credential = credential(secret, &username);
+
+    (username, credential)
+}
+
+fn credential(secret: &str, username: &str) -> String {
+    let mut mac = HmacSha1::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(username.as_bytes());
+    STANDARD.encode(mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credential_is_base64_hmac_sha1() {
+        // RFC 2202, test case 2
+        assert_eq!(credential("Jefe", "what do ya want for nothing?"), "7/zfauXrL6LSdBbV8YTfnCWafHk=");
+    }
+
+    #[test]
+    fn username_carries_expiry() {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let (username, password) = turn_credentials("secret", 600, "alice");
+
+        let (expiry, user) = username.split_once(':').unwrap();
+        let expiry: u64 = expiry.parse().unwrap();
+        assert!((now + 600..=now + 601).contains(&expiry));
+        assert_eq!(user, "alice");
+        assert_eq!(password, credential("secret", &username));
+    }
+}
@@ -0,0 +1,126 @@
+pub mod close_codes;
+pub mod config;
+pub mod ice;
+pub mod shutdown;
+pub mod signaling;
+pub mod version;
+#[cfg(feature = "webrtc")]
+pub mod webrtc_handler;
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+use config::Config;
+use ice::IceConfig;
+use signaling::{ConnectionSlot, MessageLimits, SignalingState, handle_websocket};
+use version::VersionInfo;
+use log::{info, warn};
+
+/// All HTTP and WebSocket routes of the server, with CORS applied.
+pub fn build_routes(
+    state: Arc<Mutex<SignalingState>>,
+    config: Arc<Config>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let state_filter = warp::any().map(move || state.clone());
+    let config_filter = warp::any().map(move || config.clone());
+
+    let cors = warp::cors()
+        .allow_any_origin()
+        .allow_methods(vec!["GET", "POST"])
+        .allow_headers(vec!["Content-Type"]);
+
+    let signaling = warp::path("signaling")
+        .and(warp::ws())
+        .and(state_filter.clone())
+        .and(config_filter)
+        .and(warp::addr::remote())
+        .and(warp::header::optional::<String>("x-forwarded-for"))
+        .map(
+            |ws: warp::ws::Ws,
+             state: Arc<Mutex<SignalingState>>,
+             config: Arc<Config>,
+             remote: Option<SocketAddr>,
+             forwarded_for: Option<String>| {
+                if state.lock().unwrap().draining {
+                    warn!("Сервер в режиме дренажа, новое подключение отклонено");
+                    return warp::reply::with_status("Server is draining", StatusCode::SERVICE_UNAVAILABLE)
+                        .into_response();
+                }
+                let ip = client_ip(remote, forwarded_for.as_deref(), config.trust_forwarded_for);
+                let Some(slot) = ConnectionSlot::acquire(&state, ip, config.max_connections_per_ip) else {
+                    return warp::reply::with_status("Too many connections", StatusCode::TOO_MANY_REQUESTS)
+                        .into_response();
+                };
+                let limits = MessageLimits {
+                    rate_per_sec: config.message_rate_limit,
+                    burst: config.message_burst,
+                    max_strikes: config.max_strikes,
+                };
+                // TURN credentials only go to clients holding a signaling connection
+                let ice_config = IceConfig::generate(&config);
+                info!("Новое WebSocket подключение инициировано"); // Лог перед апгрейдом
+                ws.max_message_size(config.max_message_size)
+                    .on_upgrade(move |socket| {
+                        info!("WebSocket соединение установлено"); // Лог после успешного апгрейда
+                        handle_websocket(socket, state, ice_config, limits, slot)
+                    })
+                    .into_response()
+            },
+        );
+
+    let stats = warp::path!("api" / "stats")
+        .and(warp::get())
+        .and(state_filter.clone())
+        .map(|state: Arc<Mutex<SignalingState>>| warp::reply::json(&state.lock().unwrap().stats()));
+
+    let version = warp::path!("api" / "version")
+        .and(warp::get())
+        .map(|| warp::reply::json(&VersionInfo::current()));
+
+    signaling.or(stats).or(version).with(cors)
+}
+
+/// The client's address: the last `X-Forwarded-For` entry when the proxy is trusted,
+/// otherwise the peer address of the TCP connection. Only the last entry is taken, it's
+/// the one our proxy appended; everything before it comes from the client.
+fn client_ip(remote: Option<SocketAddr>, forwarded_for: Option<&str>, trust_forwarded_for: bool) -> Option<IpAddr> {
+    if trust_forwarded_for {
+        if let Some(ip) = forwarded_for
+            .and_then(|header| header.rsplit(',').next())
+            .and_then(|hop| hop.trim().parse().ok())
+        {
+            return Some(ip);
+        }
+    }
+    remote.map(|addr| addr.ip())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PEER: &str = "10.0.0.1:50000";
+
+    #[test]
+    fn client_ip_uses_peer_address_by_default() {
+        let remote = PEER.parse().ok();
+        assert_eq!(client_ip(remote, Some("1.2.3.4"), false), "10.0.0.1".parse().ok());
+        assert_eq!(client_ip(None, None, false), None);
+    }
+
+    #[test]
+    fn client_ip_takes_last_forwarded_entry() {
+        let remote = PEER.parse().ok();
+        // The client spoofed the first entry, the proxy appended the real address
+        assert_eq!(client_ip(remote, Some("6.6.6.6, 1.2.3.4"), true), "1.2.3.4".parse().ok());
+        assert_eq!(client_ip(remote, Some(" 2001:db8::1 "), true), "2001:db8::1".parse().ok());
+    }
+
+    #[test]
+    fn client_ip_falls_back_on_bad_header() {
+        let remote = PEER.parse().ok();
+        assert_eq!(client_ip(remote, Some("1.2.3.4, garbage"), true), "10.0.0.1".parse().ok());
+        assert_eq!(client_ip(remote, None, true), "10.0.0.1".parse().ok());
+    }
+}
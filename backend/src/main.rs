@@ -1,35 +1,36 @@
-mod signaling;
-mod webrtc_handler;
-
 use std::sync::{Arc, Mutex};
-use warp::Filter;
-use signaling::{SignalingState, handle_websocket};
-use log::info;
+use backend::config::Config;
+use backend::shutdown::{drain, shutdown_signal};
+use backend::signaling::SignalingState;
+use log::{error, info};
 
 #[tokio::main]
 async fn main() {
     env_logger::init();
     info!("Запуск сигнального сервера...");
 
-    let state = Arc::new(Mutex::new(SignalingState::new()));
-    let state_filter = warp::any().map(move || state.clone());
+    let config = match Config::from_env() {
+        Ok(config) => Arc::new(config),
+        Err(e) => {
+            error!("Некорректная конфигурация: {}", e);
+            std::process::exit(1);
+        }
+    };
+    config.log_summary();
+    let port = config.port;
+    let drain_grace_period = config.drain_grace_period;
 
-    let cors = warp::cors()
-        .allow_any_origin()
-        .allow_methods(vec!["GET", "POST"])
-        .allow_headers(vec!["Content-Type"]);
+    let state = Arc::new(Mutex::new(SignalingState::new()));
+    let routes = backend::build_routes(state.clone(), config);
 
-    let signaling = warp::path("signaling")
-        .and(warp::ws())
-        .and(state_filter)
-        .map(|ws: warp::ws::Ws, state| {
-            info!("Новое WebSocket подключение инициировано"); // Лог перед апгрейдом
-            ws.on_upgrade(move |socket| {
-                info!("WebSocket соединение установлено"); // Лог после успешного апгрейда
-                handle_websocket(socket, state)
-            })
-        })
-        .with(cors);
+    info!(
+        "Версия {} ({}), собрано {}",
+        env!("CARGO_PKG_VERSION"),
+        env!("GIT_COMMIT"),
+        env!("BUILD_TIMESTAMP")
+    );
+    tokio::spawn(warp::serve(routes).run(([0, 0, 0, 0], port)));
 
-    warp::serve(signaling).run(([0, 0, 0, 0], 3030)).await;
+    shutdown_signal().await;
+    drain(&state, drain_grace_period).await;
 }
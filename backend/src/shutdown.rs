@@ -0,0 +1,88 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use log::info;
+use crate::close_codes;
+use crate::signaling::SignalingState;
+
+/// Waits for Ctrl+C or SIGTERM.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+/// Rejects new connections, gives existing clients `grace_period` to
+/// reconnect elsewhere and closes whoever is still left.
+pub async fn drain(state: &Arc<Mutex<SignalingState>>, grace_period: Duration) {
+    state.lock().unwrap().start_draining(grace_period);
+    info!("Получен сигнал завершения, дренаж соединений ({} с)", grace_period.as_secs());
+
+    let deadline = tokio::time::Instant::now() + grace_period;
+    while tokio::time::Instant::now() < deadline {
+        if state.lock().unwrap().users.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+
+    {
+        let state = state.lock().unwrap();
+        if !state.users.is_empty() {
+            info!("Закрытие оставшихся соединений: {}", state.users.len());
+            state.close_all(close_codes::DRAINED, "server is shutting down");
+        }
+    }
+
+    // Даём writer-задачам отправить Close-фреймы
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    info!("Сигнальный сервер остановлен");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+    use crate::close_codes;
+
+    #[tokio::test]
+    async fn drain_notifies_and_closes_remaining_clients() {
+        let state = Arc::new(Mutex::new(SignalingState::new()));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        state.lock().unwrap().users.insert("a".to_string(), tx);
+
+        drain(&state, Duration::ZERO).await;
+
+        let notice = rx.recv().await.unwrap();
+        assert_eq!(notice.to_str().unwrap(), r#"{"grace_secs":0,"type":"draining"}"#);
+        let close = rx.recv().await.unwrap();
+        assert_eq!(close.close_frame().map(|(code, _)| code), Some(close_codes::DRAINED));
+        assert!(state.lock().unwrap().draining);
+    }
+
+    #[tokio::test]
+    async fn drain_returns_once_clients_are_gone() {
+        let state = Arc::new(Mutex::new(SignalingState::new()));
+
+        let started = tokio::time::Instant::now();
+        drain(&state, Duration::from_secs(30)).await;
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
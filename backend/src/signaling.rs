@@ -1,14 +1,154 @@
 use std::collections::HashMap;
+use std::net::{IpAddr, Ipv6Addr};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use warp::ws::{Message, WebSocket};
 use tokio::sync::mpsc;
 use futures_util::{StreamExt, SinkExt};
-use serde_json::Value;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use uuid::Uuid;
+use log::warn;
+use crate::close_codes;
+use crate::ice::IceConfig;
+
+/// Per-connection limits on incoming messages.
+#[derive(Clone, Copy, Debug)]
+pub struct MessageLimits {
+    /// Sustained messages per second.
+    pub rate_per_sec: u32,
+    /// Messages allowed in a burst, i.e. the size of the token bucket.
+    pub burst: u32,
+    /// Rate-limited or malformed messages tolerated before the connection is closed.
+    pub max_strikes: u32,
+}
+
+/// Token bucket: `capacity` messages at once, refilled at `rate_per_sec`.
+struct RateLimiter {
+    tokens: f64,
+    capacity: f64,
+    rate_per_sec: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    fn new(rate_per_sec: u32, capacity: u32) -> Self {
+        Self {
+            tokens: capacity as f64,
+            capacity: capacity as f64,
+            rate_per_sec: rate_per_sec as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn allow(&mut self) -> bool {
+        self.allow_at(Instant::now())
+    }
+
+    fn allow_at(&mut self, now: Instant) -> bool {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate_per_sec).min(self.capacity);
+        self.last_refill = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrackKind {
+    Camera,
+    Mic,
+    Screen,
+}
+
+/// Tracks of one kind a participant may publish in a room.
+pub const MAX_TRACKS_PER_KIND: usize = 4;
+/// Longest accepted track or stream id, in bytes.
+pub const MAX_TRACK_ID_LEN: usize = 128;
+
+/// A media track a participant publishes, as announced over `track_added`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TrackInfo {
+    /// `MediaStreamTrack.id` on the publishing side.
+    pub id: String,
+    pub kind: TrackKind,
+    /// `MediaStream.id` the track is sent in, lets receivers match `ontrack` events.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_id: Option<String>,
+    #[serde(default)]
+    pub muted: bool,
+}
+
+impl TrackInfo {
+    /// Ids are stored for as long as the track lives, so they have to stay short.
+    pub fn is_valid(&self) -> bool {
+        !self.id.is_empty()
+            && self.id.len() <= MAX_TRACK_ID_LEN
+            && self.stream_id.as_ref().is_none_or(|id| id.len() <= MAX_TRACK_ID_LEN)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Can mute, remove and change roles of other participants.
+    Moderator,
+    /// Can publish tracks.
+    Presenter,
+    /// Receive-only.
+    Viewer,
+}
+
+impl Role {
+    pub fn can_publish(self) -> bool {
+        self != Role::Viewer
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Participant {
+    pub id: String,
+    pub role: Role,
+}
+
+#[derive(Serialize)]
+pub struct ParticipantState {
+    pub id: String,
+    pub role: Role,
+    pub tracks: Vec<TrackInfo>,
+}
+
+/// Body of `GET /api/stats`.
+#[derive(Serialize)]
+pub struct Stats {
+    pub connections: usize,
+    pub rooms: usize,
+    /// Upgrades refused because of the per-IP limit since startup.
+    pub rejected_connections: u64,
+    pub draining: bool,
+}
 
 pub struct SignalingState {
-    pub rooms: HashMap<String, Vec<String>>,
+    pub rooms: HashMap<String, Vec<Participant>>,
     pub users: HashMap<String, mpsc::UnboundedSender<Message>>,
+    /// Tracks published by each user, keyed by (room id, user id).
+    pub tracks: HashMap<(String, String), Vec<TrackInfo>>,
+    /// Open WebSocket connections per client IP.
+    pub connections_per_ip: HashMap<IpAddr, usize>,
+    /// Upgrades refused because of the per-IP limit since startup.
+    pub rejected_connections: u64,
+    pub draining: bool,
+}
+
+impl Default for SignalingState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SignalingState {
@@ -16,44 +156,296 @@ impl SignalingState {
         Self {
             rooms: HashMap::new(),
             users: HashMap::new(),
+            tracks: HashMap::new(),
+            connections_per_ip: HashMap::new(),
+            rejected_connections: 0,
+            draining: false,
+        }
+    }
+
+    pub fn stats(&self) -> Stats {
+        Stats {
+            connections: self.users.len(),
+            rooms: self.rooms.len(),
+            rejected_connections: self.rejected_connections,
+            draining: self.draining,
+        }
+    }
+
+    /// Stops accepting new connections and tells connected clients to move to another
+    /// instance within `grace_period`; until then their connections keep working.
+    pub fn start_draining(&mut self, grace_period: Duration) {
+        self.draining = true;
+        let notice = json!({ "type": "draining", "grace_secs": grace_period.as_secs() }).to_string();
+        for tx in self.users.values() {
+            tx.send(Message::text(notice.clone())).ok();
+        }
+    }
+
+    /// Asks every connected client to close its socket.
+    pub fn close_all(&self, code: u16, reason: &'static str) {
+        for tx in self.users.values() {
+            tx.send(Message::close_with(code, reason)).ok();
         }
     }
     
     pub fn broadcast_to_room(&self, room_id: &str, sender_id: &str, message: &str) {
-        if let Some(users) = self.rooms.get(room_id) {
-            for user_id in users {
+        if let Some(participants) = self.rooms.get(room_id) {
+            for participant in participants {
                 // Don't send back to the sender
-                if user_id != sender_id {
-                    if let Some(tx) = self.users.get(user_id) {
-                        tx.send(Message::text(message)).ok();
-                    }
+                if participant.id != sender_id {
+                    self.send_to(&participant.id, message);
                 }
             }
         }
     }
 
-    pub fn join_room(&mut self, room_id: &str, user_id: &str) {
+    /// Like `broadcast_to_room`, but including the sender.
+    pub fn notify_room(&self, room_id: &str, message: &str) {
+        if let Some(participants) = self.rooms.get(room_id) {
+            for participant in participants {
+                self.send_to(&participant.id, message);
+            }
+        }
+    }
+
+    pub fn send_to(&self, user_id: &str, message: &str) {
+        if let Some(tx) = self.users.get(user_id) {
+            tx.send(Message::text(message)).ok();
+        }
+    }
+
+    pub fn close(&self, user_id: &str, code: u16, reason: &'static str) {
+        if let Some(tx) = self.users.get(user_id) {
+            tx.send(Message::close_with(code, reason)).ok();
+        }
+    }
+
+    pub fn send_error(&self, user_id: &str, reason: &str) {
+        self.send_to(user_id, &json!({ "type": "error", "reason": reason }).to_string());
+    }
+
+    /// Adds the user to the room and returns their role. The first participant becomes
+    /// the moderator; later ones are presenters unless they ask to join as viewers.
+    pub fn join_room(&mut self, room_id: &str, user_id: &str, requested: Option<Role>) -> Role {
         let room = self.rooms.entry(room_id.to_string()).or_default();
-        if !room.contains(&user_id.to_string()) {
-            room.push(user_id.to_string());
+        if let Some(existing) = room.iter().find(|p| p.id == user_id) {
+            return existing.role;
         }
+        let role = if room.is_empty() {
+            Role::Moderator
+        } else if requested == Some(Role::Viewer) {
+            Role::Viewer
+        } else {
+            Role::Presenter
+        };
+        room.push(Participant {
+            id: user_id.to_string(),
+            role,
+        });
+        role
     }
 
-    // pub fn leave_room(&mut self, room_id: &str, user_id: &str) {
-    //     if let Some(users) = self.rooms.get_mut(room_id) {
-    //         users.retain(|id| id != user_id);
-    //         if users.is_empty() {
-    //             self.rooms.remove(room_id);
-    //         }
-    //     }
-    // }
+    pub fn role_of(&self, room_id: &str, user_id: &str) -> Option<Role> {
+        self.rooms
+            .get(room_id)?
+            .iter()
+            .find(|p| p.id == user_id)
+            .map(|p| p.role)
+    }
+
+    /// Changes the user's role and returns the tracks they lost the right to publish.
+    /// Fails with the error reason for the client if the user isn't in the room or is
+    /// the room's last moderator.
+    pub fn set_role(&mut self, room_id: &str, user_id: &str, role: Role) -> Result<Vec<TrackInfo>, &'static str> {
+        let participants = self.rooms.get_mut(room_id).ok_or("unknown_user")?;
+        let moderators = participants.iter().filter(|p| p.role == Role::Moderator).count();
+        let participant = participants
+            .iter_mut()
+            .find(|p| p.id == user_id)
+            .ok_or("unknown_user")?;
+        if participant.role == Role::Moderator && role != Role::Moderator && moderators == 1 {
+            return Err("last_moderator");
+        }
+        participant.role = role;
+
+        if role.can_publish() {
+            return Ok(Vec::new());
+        }
+        Ok(self
+            .tracks
+            .remove(&(room_id.to_string(), user_id.to_string()))
+            .unwrap_or_default())
+    }
+
+    /// Removes the user and their tracks from the room, dropping the room once empty. If the
+    /// last moderator leaves, the longest-present participant is promoted and returned.
+    pub fn leave_room(&mut self, room_id: &str, user_id: &str) -> Option<Participant> {
+        let participants = self.rooms.get_mut(room_id)?;
+        let index = participants.iter().position(|p| p.id == user_id)?;
+        let left = participants.remove(index);
+        self.tracks.remove(&(room_id.to_string(), user_id.to_string()));
+        if participants.is_empty() {
+            self.rooms.remove(room_id);
+            return None;
+        }
+        if left.role == Role::Moderator && !participants.iter().any(|p| p.role == Role::Moderator) {
+            let next = &mut participants[0];
+            next.role = Role::Moderator;
+            return Some(next.clone());
+        }
+        None
+    }
+
+    /// `leave_room` plus `peer_left` / `role_changed` notifications for the rest of the room.
+    pub fn leave_room_and_notify(&mut self, room_id: &str, user_id: &str) {
+        if self.role_of(room_id, user_id).is_none() {
+            return;
+        }
+        let promoted = self.leave_room(room_id, user_id);
+        let left = json!({ "type": "peer_left", "room": room_id, "from": user_id });
+        self.broadcast_to_room(room_id, user_id, &left.to_string());
+        if let Some(participant) = promoted {
+            self.notify_room(room_id, &role_changed(room_id, &participant));
+        }
+    }
+
+    /// Adds a track, replacing an earlier announcement with the same id. Returns false if
+    /// the user already publishes `MAX_TRACKS_PER_KIND` tracks of this kind in the room.
+    pub fn add_track(&mut self, room_id: &str, user_id: &str, track: TrackInfo) -> bool {
+        let tracks = self.tracks.entry((room_id.to_string(), user_id.to_string())).or_default();
+        tracks.retain(|t| t.id != track.id);
+        if tracks.iter().filter(|t| t.kind == track.kind).count() >= MAX_TRACKS_PER_KIND {
+            return false;
+        }
+        tracks.push(track);
+        true
+    }
+
+    pub fn remove_track(&mut self, room_id: &str, user_id: &str, track_id: &str) -> bool {
+        match self.tracks.get_mut(&(room_id.to_string(), user_id.to_string())) {
+            Some(tracks) => {
+                let before = tracks.len();
+                tracks.retain(|t| t.id != track_id);
+                tracks.len() != before
+            }
+            None => false,
+        }
+    }
+
+    pub fn set_track_muted(&mut self, room_id: &str, user_id: &str, track_id: &str, muted: bool) -> bool {
+        match self
+            .tracks
+            .get_mut(&(room_id.to_string(), user_id.to_string()))
+            .and_then(|tracks| tracks.iter_mut().find(|t| t.id == track_id))
+        {
+            Some(track) => {
+                track.muted = muted;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Everyone in the room except `user_id`, sent to a participant on join.
+    pub fn room_participants(&self, room_id: &str, user_id: &str) -> Vec<ParticipantState> {
+        self.rooms
+            .get(room_id)
+            .into_iter()
+            .flatten()
+            .filter(|p| p.id != user_id)
+            .map(|p| ParticipantState {
+                id: p.id.clone(),
+                role: p.role,
+                tracks: self
+                    .tracks
+                    .get(&(room_id.to_string(), p.id.clone()))
+                    .cloned()
+                    .unwrap_or_default(),
+            })
+            .collect()
+    }
 
     // pub fn get_room_users(&self, room_id: &str) -> Vec<String> {
     //     self.rooms.get(room_id).cloned().unwrap_or_default()
     // }
 }
 
-pub async fn handle_websocket(ws: WebSocket, state: Arc<Mutex<SignalingState>>) {
+fn role_changed(room_id: &str, participant: &Participant) -> String {
+    json!({
+        "type": "role_changed",
+        "room": room_id,
+        "user_id": participant.id,
+        "role": participant.role,
+    })
+    .to_string()
+}
+
+/// One of the per-IP connection slots, released when dropped. Held for the whole
+/// lifetime of a WebSocket, so a failed upgrade gives its slot back too.
+pub struct ConnectionSlot {
+    state: Arc<Mutex<SignalingState>>,
+    ip: Option<IpAddr>,
+}
+
+impl ConnectionSlot {
+    /// Takes a slot for `ip`, or returns `None` if it already has `limit` connections.
+    /// Connections with an unknown address are not limited.
+    pub fn acquire(state: &Arc<Mutex<SignalingState>>, ip: Option<IpAddr>, limit: usize) -> Option<Self> {
+        let ip = ip.map(limit_key);
+        if let Some(ip) = ip {
+            let mut guard = state.lock().unwrap();
+            let state = &mut *guard;
+            let count = state.connections_per_ip.entry(ip).or_default();
+            if *count >= limit {
+                state.rejected_connections += 1;
+                warn!(
+                    "Превышен лимит подключений ({}) для {}, всего отклонено: {}",
+                    limit, ip, state.rejected_connections
+                );
+                return None;
+            }
+            *count += 1;
+        }
+        Some(Self {
+            state: state.clone(),
+            ip,
+        })
+    }
+}
+
+/// The address connections are counted under. An IPv6 client typically owns a whole /64
+/// and could use a fresh address for every socket, so IPv6 is counted per /64 prefix.
+fn limit_key(ip: IpAddr) -> IpAddr {
+    match ip.to_canonical() {
+        IpAddr::V6(v6) => IpAddr::V6(Ipv6Addr::from(u128::from(v6) & !(u64::MAX as u128))),
+        v4 => v4,
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        if let Some(ip) = self.ip {
+            let mut state = self.state.lock().unwrap();
+            if let Some(count) = state.connections_per_ip.get_mut(&ip) {
+                *count -= 1;
+                if *count == 0 {
+                    state.connections_per_ip.remove(&ip);
+                }
+            }
+        }
+    }
+}
+
+/// Sends the client its `ice_config` and relays its messages until it disconnects.
+/// `slot` is only held so that it's released when the connection ends.
+pub async fn handle_websocket(
+    ws: WebSocket,
+    state: Arc<Mutex<SignalingState>>,
+    ice_config: IceConfig,
+    limits: MessageLimits,
+    _slot: ConnectionSlot,
+) {
     let (mut ws_tx, mut ws_rx) = ws.split();
     let (tx, mut rx) = mpsc::unbounded_channel();
 
@@ -62,48 +454,380 @@ pub async fn handle_websocket(ws: WebSocket, state: Arc<Mutex<SignalingState>>)
     {
         let mut state = state.lock().unwrap();
         state.users.insert(user_id.clone(), tx);
+        let mut message = json!(ice_config);
+        message["type"] = json!("ice_config");
+        state.send_to(&user_id, &message.to_string());
     }
 
-    let state_clone = state.clone();
     tokio::spawn(async move {
         while let Some(msg) = rx.recv().await {
             ws_tx.send(msg).await.ok();
         }
     });
 
+    let mut limiter = RateLimiter::new(limits.rate_per_sec, limits.burst);
+    let mut strikes = 0;
+
     while let Some(result) = ws_rx.next().await {
         if let Ok(msg) = result {
-            if let Ok(text) = msg.to_str() {
-                let mut json_val = serde_json::from_str::<Value>(text).unwrap();
-                // Добавляем sender ID
-                json_val["from"] = Value::String(user_id.clone());
-        
-                if let Some(msg_type) = json_val.get("type").and_then(|v| v.as_str()) {
-                    match msg_type {
-                        "join" => {
-                            if let Some(room) = json_val.get("room").and_then(|r| r.as_str()) {
-                                state.lock().unwrap().join_room(room, &user_id);
-                            }
-                        }
-                        "offer" | "answer" | "candidate" => {
-                            if let Some(room) = json_val.get("room").and_then(|r| r.as_str()) {
-                                // Для сообщений, относящихся к комнате
-                                let msg_text = serde_json::to_string(&json_val).unwrap();
-                                state.lock().unwrap().broadcast_to_room(room, &user_id, &msg_text);
-                            }
+            if !limiter.allow() {
+                strikes += 1;
+                state.lock().unwrap().send_error(&user_id, "rate_limited");
+            } else if let Ok(text) = msg.to_str() {
+                match serde_json::from_str::<Value>(text) {
+                    Ok(json_val) if json_val.is_object() => {
+                        if !handle_message(&state, &user_id, json_val) {
+                            strikes += 1;
                         }
-                        _ => {}
+                    }
+                    _ => {
+                        strikes += 1;
+                        state.lock().unwrap().send_error(&user_id, "invalid_message");
                     }
                 }
             }
+
+            if strikes >= limits.max_strikes {
+                warn!("Соединение {} закрыто за нарушения лимитов ({} предупреждений)", user_id, strikes);
+                state
+                    .lock()
+                    .unwrap()
+                    .close(&user_id, close_codes::RATE_LIMITED, "too many invalid or rate-limited messages");
+                break;
+            }
         } else {
             break;
         }
     }
 
     let mut state = state.lock().unwrap();
+    let joined_rooms: Vec<String> = state
+        .rooms
+        .iter()
+        .filter(|(_, participants)| participants.iter().any(|p| p.id == user_id))
+        .map(|(room, _)| room.clone())
+        .collect();
+    for room in &joined_rooms {
+        state.leave_room_and_notify(room, &user_id);
+    }
+
     state.users.remove(&user_id);
-    for (_, users) in state.rooms.iter_mut() {
-        users.retain(|id| id != &user_id);
+}
+
+/// Returns false if the message was malformed, which counts as a strike.
+fn handle_message(state: &Arc<Mutex<SignalingState>>, user_id: &str, mut json_val: Value) -> bool {
+    // Добавляем sender ID
+    json_val["from"] = Value::String(user_id.to_string());
+
+    if let Some(msg_type) = json_val.get("type").and_then(|v| v.as_str()) {
+        // Роль отправителя в комнате, к которой относится сообщение
+        let role = json_val
+            .get("room")
+            .and_then(|r| r.as_str())
+            .and_then(|room| state.lock().unwrap().role_of(room, user_id));
+
+        match msg_type {
+            "join" => {
+                if let Some(room) = json_val.get("room").and_then(|r| r.as_str()) {
+                    let requested = json_val
+                        .get("role")
+                        .cloned()
+                        .and_then(|r| serde_json::from_value::<Role>(r).ok());
+                    let mut state = state.lock().unwrap();
+                    let role = state.join_room(room, user_id, requested);
+                    // Новому участнику — его роль и состояние тех, кто уже в комнате
+                    let snapshot = json!({
+                        "type": "room_state",
+                        "room": room,
+                        "user_id": user_id,
+                        "role": role,
+                        "participants": state.room_participants(room, user_id),
+                    });
+                    state.send_to(user_id, &snapshot.to_string());
+                }
+            }
+            "track_added" | "track_removed" | "track_muted"
+                if !role.is_some_and(Role::can_publish) =>
+            {
+                state.lock().unwrap().send_error(user_id, "forbidden");
+            }
+            "track_added" => {
+                let track = json_val
+                    .get("track")
+                    .cloned()
+                    .and_then(|t| serde_json::from_value::<TrackInfo>(t).ok())
+                    .filter(TrackInfo::is_valid);
+                let Some(track) = track else {
+                    state.lock().unwrap().send_error(user_id, "invalid_message");
+                    return false;
+                };
+                if let Some(room) = json_val.get("room").and_then(|r| r.as_str()) {
+                    let mut announcement = json_val.clone();
+                    announcement["track"] = json!(track);
+                    let mut state = state.lock().unwrap();
+                    if state.add_track(room, user_id, track) {
+                        state.broadcast_to_room(room, user_id, &announcement.to_string());
+                    } else {
+                        state.send_error(user_id, "too_many_tracks");
+                    }
+                }
+            }
+            "track_removed" => {
+                if let (Some(room), Some(track_id)) = (
+                    json_val.get("room").and_then(|r| r.as_str()),
+                    json_val.get("track_id").and_then(|t| t.as_str()),
+                ) {
+                    let mut state = state.lock().unwrap();
+                    if state.remove_track(room, user_id, track_id) {
+                        state.broadcast_to_room(room, user_id, &json_val.to_string());
+                    }
+                }
+            }
+            "track_muted" => {
+                if let (Some(room), Some(track_id), Some(muted)) = (
+                    json_val.get("room").and_then(|r| r.as_str()),
+                    json_val.get("track_id").and_then(|t| t.as_str()),
+                    json_val.get("muted").and_then(|m| m.as_bool()),
+                ) {
+                    let mut state = state.lock().unwrap();
+                    if state.set_track_muted(room, user_id, track_id, muted) {
+                        state.broadcast_to_room(room, user_id, &json_val.to_string());
+                    }
+                }
+            }
+            "mute_user" | "remove_user" | "set_role" if role != Some(Role::Moderator) => {
+                state.lock().unwrap().send_error(user_id, "forbidden");
+            }
+            "mute_user" => {
+                if let (Some(room), Some(target)) = (
+                    json_val.get("room").and_then(|r| r.as_str()),
+                    json_val.get("target").and_then(|t| t.as_str()),
+                ) {
+                    let state = state.lock().unwrap();
+                    if state.role_of(room, target).is_some() {
+                        // Клиент сам выключает треки и сообщает об этом через track_muted
+                        state.send_to(target, &json_val.to_string());
+                    } else {
+                        state.send_error(user_id, "unknown_user");
+                    }
+                }
+            }
+            "remove_user" => {
+                if let (Some(room), Some(target)) = (
+                    json_val.get("room").and_then(|r| r.as_str()),
+                    json_val.get("target").and_then(|t| t.as_str()),
+                ) {
+                    let mut state = state.lock().unwrap();
+                    if state.role_of(room, target).is_some() {
+                        let removed = json!({ "type": "removed", "room": room, "from": user_id });
+                        state.send_to(target, &removed.to_string());
+                        state.leave_room_and_notify(room, target);
+                        // Соединение без других комнат больше не нужно. Это не бан:
+                        // новое подключение может снова войти в комнату
+                        if !state.rooms.values().flatten().any(|p| p.id == target) {
+                            state.close(target, close_codes::REMOVED, "removed by a moderator");
+                        }
+                    } else {
+                        state.send_error(user_id, "unknown_user");
+                    }
+                }
+            }
+            "set_role" => {
+                let role = json_val
+                    .get("role")
+                    .cloned()
+                    .and_then(|r| serde_json::from_value::<Role>(r).ok());
+                if let (Some(room), Some(target), Some(role)) = (
+                    json_val.get("room").and_then(|r| r.as_str()),
+                    json_val.get("target").and_then(|t| t.as_str()),
+                    role,
+                ) {
+                    let mut state = state.lock().unwrap();
+                    match state.set_role(room, target, role) {
+                        Ok(dropped) => {
+                            // Зрителю публиковать нельзя: остальные убирают его треки,
+                            // сам он прекращает отправку по role_changed
+                            for track in dropped {
+                                let removed = json!({
+                                    "type": "track_removed",
+                                    "room": room,
+                                    "track_id": track.id,
+                                    "from": target,
+                                });
+                                state.broadcast_to_room(room, target, &removed.to_string());
+                            }
+                            let participant = Participant { id: target.to_string(), role };
+                            state.notify_room(room, &role_changed(room, &participant));
+                        }
+                        Err(reason) => state.send_error(user_id, reason),
+                    }
+                }
+            }
+            "offer" | "answer" | "candidate" if role.is_none() => {
+                state.lock().unwrap().send_error(user_id, "not_in_room");
+            }
+            "offer" | "answer" | "candidate" => {
+                if let Some(room) = json_val.get("room").and_then(|r| r.as_str()) {
+                    // Для сообщений, относящихся к комнате
+                    let msg_text = serde_json::to_string(&json_val).unwrap();
+                    state.lock().unwrap().broadcast_to_room(room, user_id, &msg_text);
+                }
+            }
+            _ => {}
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn track(id: &str) -> TrackInfo {
+        TrackInfo {
+            id: id.to_string(),
+            kind: TrackKind::Camera,
+            stream_id: None,
+            muted: false,
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn rate_limiter_allows_burst_then_refills() {
+        let mut limiter = RateLimiter::new(10, 3);
+        let start = limiter.last_refill;
+        assert!((0..3).all(|_| limiter.allow_at(start)));
+        assert!(!limiter.allow_at(start));
+
+        // 10 per second: one token back after 100 ms, never more than the burst
+        assert!(limiter.allow_at(start + Duration::from_millis(100)));
+        assert!(!limiter.allow_at(start + Duration::from_millis(100)));
+        let later = start + Duration::from_secs(60);
+        assert!((0..3).all(|_| limiter.allow_at(later)));
+        assert!(!limiter.allow_at(later));
+    }
+
+    #[test]
+    fn first_participant_moderates() {
+        let mut state = SignalingState::new();
+        assert_eq!(state.join_room("r", "a", Some(Role::Viewer)), Role::Moderator);
+        assert_eq!(state.join_room("r", "b", None), Role::Presenter);
+        assert_eq!(state.join_room("r", "c", Some(Role::Viewer)), Role::Viewer);
+        // Joining again keeps the role
+        assert_eq!(state.join_room("r", "b", Some(Role::Viewer)), Role::Presenter);
+        assert_eq!(state.rooms["r"].len(), 3);
+    }
+
+    #[test]
+    fn leaving_moderator_promotes_next_participant() {
+        let mut state = SignalingState::new();
+        state.join_room("r", "a", None);
+        state.join_room("r", "b", Some(Role::Viewer));
+        state.join_room("r", "c", None);
+
+        let promoted = state.leave_room("r", "a").unwrap();
+        assert_eq!(promoted.id, "b");
+        assert_eq!(state.role_of("r", "b"), Some(Role::Moderator));
+
+        assert!(state.leave_room("r", "c").is_none());
+        assert!(state.leave_room("r", "b").is_none());
+        assert!(!state.rooms.contains_key("r"));
+    }
+
+    #[test]
+    fn last_moderator_cannot_be_demoted() {
+        let mut state = SignalingState::new();
+        state.join_room("r", "a", None);
+        state.join_room("r", "b", None);
+
+        assert_eq!(state.set_role("r", "a", Role::Presenter).err(), Some("last_moderator"));
+        assert_eq!(state.set_role("r", "x", Role::Presenter).err(), Some("unknown_user"));
+
+        state.set_role("r", "b", Role::Moderator).unwrap();
+        state.set_role("r", "a", Role::Presenter).unwrap();
+        assert_eq!(state.role_of("r", "a"), Some(Role::Presenter));
+    }
+
+    #[test]
+    fn demotion_to_viewer_drops_tracks() {
+        let mut state = SignalingState::new();
+        state.join_room("r", "a", None);
+        state.join_room("r", "b", None);
+        state.add_track("r", "b", track("cam"));
+
+        let dropped = state.set_role("r", "b", Role::Viewer).unwrap();
+        assert_eq!(dropped.len(), 1);
+        assert!(state.room_participants("r", "a")[0].tracks.is_empty());
+    }
+
+    #[test]
+    fn tracks_are_scoped_to_room() {
+        let mut state = SignalingState::new();
+        for room in ["r1", "r2"] {
+            state.join_room(room, "a", None);
+            state.join_room(room, "b", None);
+        }
+        state.add_track("r1", "b", track("cam"));
+        state.add_track("r1", "b", track("cam"));
+
+        assert_eq!(state.room_participants("r1", "a")[0].tracks.len(), 1);
+        assert!(state.room_participants("r2", "a")[0].tracks.is_empty());
+
+        // Leaving clears them, re-joining starts empty
+        state.leave_room("r1", "b");
+        state.join_room("r1", "b", None);
+        assert!(state.room_participants("r1", "a")[0].tracks.is_empty());
+    }
+
+    #[test]
+    fn tracks_are_capped_per_kind() {
+        let mut state = SignalingState::new();
+        state.join_room("r", "a", None);
+        for i in 0..MAX_TRACKS_PER_KIND {
+            assert!(state.add_track("r", "a", track(&format!("cam{}", i))));
+        }
+        assert!(!state.add_track("r", "a", track("one-too-many")));
+        // Re-announcing a known track and other kinds are still fine
+        assert!(state.add_track("r", "a", track("cam0")));
+        let mic = TrackInfo { kind: TrackKind::Mic, ..track("mic") };
+        assert!(state.add_track("r", "a", mic));
+    }
+
+    #[test]
+    fn long_track_ids_are_invalid() {
+        assert!(track(&"x".repeat(MAX_TRACK_ID_LEN)).is_valid());
+        assert!(!track(&"x".repeat(MAX_TRACK_ID_LEN + 1)).is_valid());
+        assert!(!track("").is_valid());
+        let stream_id = Some("s".repeat(MAX_TRACK_ID_LEN + 1));
+        assert!(!TrackInfo { stream_id, ..track("cam") }.is_valid());
+    }
+
+    #[test]
+    fn connection_slots_are_limited_per_ip() {
+        let state = Arc::new(Mutex::new(SignalingState::new()));
+        let ip = "10.0.0.1".parse().ok();
+
+        let first = ConnectionSlot::acquire(&state, ip, 2).unwrap();
+        let _second = ConnectionSlot::acquire(&state, ip, 2).unwrap();
+        assert!(ConnectionSlot::acquire(&state, ip, 2).is_none());
+        assert!(ConnectionSlot::acquire(&state, "10.0.0.2".parse().ok(), 2).is_some());
+        assert_eq!(state.lock().unwrap().rejected_connections, 1);
+
+        drop(first);
+        assert!(ConnectionSlot::acquire(&state, ip, 2).is_some());
+    }
+
+    #[test]
+    fn ipv6_is_limited_per_prefix() {
+        let state = Arc::new(Mutex::new(SignalingState::new()));
+
+        let _first = ConnectionSlot::acquire(&state, "2001:db8:1:2::1".parse().ok(), 1).unwrap();
+        assert!(ConnectionSlot::acquire(&state, "2001:db8:1:2:ffff::9".parse().ok(), 1).is_none());
+        assert!(ConnectionSlot::acquire(&state, "2001:db8:1:3::1".parse().ok(), 1).is_some());
+
+        // IPv4-mapped addresses count as the IPv4 address
+        let _v4 = ConnectionSlot::acquire(&state, "10.0.0.1".parse().ok(), 1).unwrap();
+        assert!(ConnectionSlot::acquire(&state, "::ffff:10.0.0.1".parse().ok(), 1).is_none());
+    }
+}
@@ -0,0 +1,31 @@
+use serde::Serialize;
+
+/// Build information reported by `GET /api/version`.
+#[derive(Serialize)]
+pub struct VersionInfo {
+    pub version: &'static str,
+    pub git_commit: &'static str,
+    /// Unix time (seconds) the binary was built at.
+    pub build_timestamp: u64,
+    /// Cargo features the binary was compiled with.
+    pub features: Vec<&'static str>,
+}
+
+impl VersionInfo {
+    pub fn current() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            git_commit: env!("GIT_COMMIT"),
+            build_timestamp: env!("BUILD_TIMESTAMP").parse().unwrap_or_default(),
+            features: enabled_features(),
+        }
+    }
+}
+
+fn enabled_features() -> Vec<&'static str> {
+    let mut features = Vec::new();
+    if cfg!(feature = "webrtc") {
+        features.push("webrtc");
+    }
+    features
+}
@@ -5,13 +5,20 @@ use webrtc::peer_connection::configuration::RTCConfiguration;
 use webrtc::peer_connection::RTCPeerConnection;
 use webrtc::track::track_local::track_local_static_rtp::TrackLocalStaticRTP;
 use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
+use crate::ice::IceConfig;
 
-pub async fn create_peer_connection() -> Result<RTCPeerConnection, webrtc::Error> {
+pub async fn create_peer_connection(ice_config: &IceConfig) -> Result<RTCPeerConnection, webrtc::Error> {
     let config = RTCConfiguration {
-        ice_servers: vec![RTCIceServer {
-            urls: vec!["stun:stun.l.google.com:19302".to_string()],
-            ..Default::default()
-        }],
+        ice_servers: ice_config
+            .ice_servers
+            .iter()
+            .map(|server| RTCIceServer {
+                urls: server.urls.clone(),
+                username: server.username.clone().unwrap_or_default(),
+                credential: server.credential.clone().unwrap_or_default(),
+                ..Default::default()
+            })
+            .collect(),
         ..Default::default()
     };
 
@@ -0,0 +1,216 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use backend::config::Config;
+use backend::signaling::SignalingState;
+use serde_json::{json, Value};
+use warp::http::StatusCode;
+use warp::test::WsClient;
+use warp::ws::Message;
+use warp::{Filter, Reply};
+
+fn config(vars: &[(&str, &str)]) -> Arc<Config> {
+    let config = Config::from_lookup(|key| vars.iter().find(|(k, _)| *k == key).map(|(_, v)| v.to_string()));
+    Arc::new(config.unwrap())
+}
+
+fn state() -> Arc<Mutex<SignalingState>> {
+    Arc::new(Mutex::new(SignalingState::new()))
+}
+
+fn json_of(message: Message) -> Value {
+    serde_json::from_str(message.to_str().unwrap()).unwrap()
+}
+
+/// Opens a signaling connection and consumes the `ice_config` every client gets first.
+async fn connect<F>(routes: F) -> WsClient
+where
+    F: Filter + Clone + Send + Sync + 'static,
+    F::Extract: Reply + Send,
+{
+    let mut client = warp::test::ws().path("/signaling").handshake(routes).await.unwrap();
+    assert_eq!(json_of(client.recv().await.unwrap())["type"], "ice_config");
+    client
+}
+
+async fn send(client: &mut WsClient, message: Value) {
+    client.send_text(message.to_string()).await;
+}
+
+async fn recv(client: &mut WsClient) -> Value {
+    json_of(client.recv().await.unwrap())
+}
+
+/// Joins `room` and returns the `room_state` reply.
+async fn join(client: &mut WsClient, room: &str, role: Option<&str>) -> Value {
+    send(client, json!({ "type": "join", "room": room, "role": role })).await;
+    let joined = recv(client).await;
+    assert_eq!(joined["type"], "room_state");
+    joined
+}
+
+#[tokio::test]
+async fn version() {
+    let routes = backend::build_routes(state(), config(&[]));
+
+    let response = warp::test::request().path("/api/version").reply(&routes).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+}
+
+#[tokio::test]
+async fn ice_config_is_sent_on_connect() {
+    let config = config(&[("TURN_URLS", "turn:turn.example:3478"), ("TURN_SECRET", "secret")]);
+    let routes = backend::build_routes(state(), config);
+
+    let mut client = warp::test::ws().path("/signaling").handshake(routes).await.unwrap();
+    let ice = recv(&mut client).await;
+    assert_eq!(ice["type"], "ice_config");
+    assert_eq!(ice["iceServers"][0]["urls"][0], "stun:stun.l.google.com:19302");
+    assert_eq!(ice["iceServers"][1]["urls"][0], "turn:turn.example:3478");
+    assert!(ice["iceServers"][1]["credential"].is_string());
+    assert_eq!(ice["ttl"], 3600);
+}
+
+#[tokio::test]
+async fn join_returns_room_state() {
+    let routes = backend::build_routes(state(), config(&[]));
+
+    let mut first = connect(routes.clone()).await;
+    let joined = join(&mut first, "r", None).await;
+    assert_eq!(joined["role"], "moderator");
+    assert!(joined["user_id"].is_string());
+
+    let mut second = connect(routes).await;
+    let joined = join(&mut second, "r", Some("viewer")).await;
+    assert_eq!(joined["role"], "viewer");
+    assert_eq!(joined["participants"][0]["role"], "moderator");
+}
+
+#[tokio::test]
+async fn viewers_cannot_publish() {
+    let routes = backend::build_routes(state(), config(&[]));
+    let mut moderator = connect(routes.clone()).await;
+    join(&mut moderator, "r", None).await;
+    let mut viewer = connect(routes).await;
+    join(&mut viewer, "r", Some("viewer")).await;
+
+    let track = json!({ "id": "cam", "kind": "camera" });
+    send(&mut viewer, json!({ "type": "track_added", "room": "r", "track": track })).await;
+    assert_eq!(recv(&mut viewer).await["reason"], "forbidden");
+    send(&mut viewer, json!({ "type": "track_muted", "room": "r", "track_id": "cam", "muted": true })).await;
+    assert_eq!(recv(&mut viewer).await["reason"], "forbidden");
+}
+
+#[tokio::test]
+async fn only_moderators_manage_participants() {
+    let routes = backend::build_routes(state(), config(&[]));
+    let mut moderator = connect(routes.clone()).await;
+    let moderator_id = join(&mut moderator, "r", None).await["user_id"].clone();
+    let mut presenter = connect(routes).await;
+    join(&mut presenter, "r", None).await;
+
+    send(&mut presenter, json!({ "type": "remove_user", "room": "r", "target": moderator_id })).await;
+    assert_eq!(recv(&mut presenter).await["reason"], "forbidden");
+    let demote = json!({ "type": "set_role", "room": "r", "target": moderator_id, "role": "viewer" });
+    send(&mut presenter, demote).await;
+    assert_eq!(recv(&mut presenter).await["reason"], "forbidden");
+    send(&mut presenter, json!({ "type": "mute_user", "room": "r", "target": moderator_id })).await;
+    assert_eq!(recv(&mut presenter).await["reason"], "forbidden");
+}
+
+#[tokio::test]
+async fn non_members_cannot_signal_into_a_room() {
+    let routes = backend::build_routes(state(), config(&[]));
+    let mut member = connect(routes.clone()).await;
+    join(&mut member, "r", None).await;
+    let mut outsider = connect(routes).await;
+
+    send(&mut outsider, json!({ "type": "offer", "room": "r", "offer": {} })).await;
+    assert_eq!(recv(&mut outsider).await["reason"], "not_in_room");
+}
+
+#[tokio::test]
+async fn invalid_messages_close_the_connection() {
+    let routes = backend::build_routes(state(), config(&[("MAX_STRIKES", "3")]));
+
+    let mut client = connect(routes).await;
+    join(&mut client, "r", None).await;
+    client.send_text("not json").await;
+    send(&mut client, json!(["not", "an", "object"])).await;
+    // Oversized track ids are rejected before they are stored
+    let track = json!({ "id": "x".repeat(200), "kind": "camera" });
+    send(&mut client, json!({ "type": "track_added", "room": "r", "track": track })).await;
+    for _ in 0..3 {
+        assert_eq!(recv(&mut client).await["reason"], "invalid_message");
+    }
+    // The test client consumes the close frame itself, so only the closing is visible
+    client.recv_closed().await.unwrap();
+}
+
+#[tokio::test]
+async fn connections_are_limited_per_ip() {
+    let state = state();
+    let config = config(&[("MAX_CONNECTIONS_PER_IP", "1"), ("TRUST_FORWARDED_FOR", "1")]);
+    let routes = backend::build_routes(state.clone(), config);
+
+    let _first = warp::test::ws()
+        .path("/signaling")
+        .header("x-forwarded-for", "1.2.3.4")
+        .handshake(routes.clone())
+        .await
+        .unwrap();
+    let second = warp::test::ws()
+        .path("/signaling")
+        .header("x-forwarded-for", "6.6.6.6, 1.2.3.4")
+        .handshake(routes.clone())
+        .await;
+    assert!(second.is_err());
+
+    let response = warp::test::request().path("/api/stats").reply(&routes).await;
+    let stats: Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(stats["rejected_connections"], 1);
+    assert_eq!(stats["connections"], 1);
+}
+
+#[tokio::test]
+async fn draining_rejects_new_connections() {
+    let state = state();
+    let routes = backend::build_routes(state.clone(), config(&[]));
+    state.lock().unwrap().start_draining(Duration::from_secs(30));
+
+    let client = warp::test::ws().path("/signaling").handshake(routes).await;
+    assert!(client.is_err());
+}
+
+#[tokio::test]
+async fn removed_participant_is_disconnected() {
+    let routes = backend::build_routes(state(), config(&[]));
+    let mut moderator = connect(routes.clone()).await;
+    join(&mut moderator, "r", None).await;
+    let mut guest = connect(routes).await;
+    let guest_id = join(&mut guest, "r", None).await["user_id"].clone();
+
+    send(&mut moderator, json!({ "type": "remove_user", "room": "r", "target": guest_id })).await;
+    assert_eq!(recv(&mut guest).await["type"], "removed");
+    guest.recv_closed().await.unwrap();
+    assert_eq!(recv(&mut moderator).await["type"], "peer_left");
+}
+
+#[tokio::test]
+async fn removal_keeps_other_rooms() {
+    let routes = backend::build_routes(state(), config(&[]));
+    let mut moderator = connect(routes.clone()).await;
+    join(&mut moderator, "r1", None).await;
+    let mut guest = connect(routes).await;
+    let guest_id = join(&mut guest, "r1", None).await["user_id"].clone();
+    join(&mut guest, "r2", None).await;
+
+    send(&mut moderator, json!({ "type": "remove_user", "room": "r1", "target": guest_id })).await;
+    assert_eq!(recv(&mut guest).await["type"], "removed");
+
+    // Still connected and still a member of r2
+    send(&mut guest, json!({ "type": "offer", "room": "r2", "offer": {} })).await;
+    send(&mut guest, json!({ "type": "offer", "room": "r1", "offer": {} })).await;
+    assert_eq!(recv(&mut guest).await["reason"], "not_in_room");
+}